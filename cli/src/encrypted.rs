@@ -0,0 +1,173 @@
+// RGB smart contracts for Bitcoin & Lightning
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2019-2023 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2019-2023 LNP/BP Standards Association. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Encrypted stock-at-rest persistence, mirroring the approach bitmask-core
+//! layers on top of `rgbstd::persistence::Stock`: the stock is strict-encoded
+//! as usual and then wrapped in an AEAD envelope before hitting disk, so a
+//! stolen `data_dir` does not leak contract state, consignments, or seal
+//! definitions.
+
+use std::fs;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use rgb::WalletError;
+use rgbstd::persistence::Stock;
+use strict_types::encoding::{StrictDeserialize, StrictSerialize};
+
+/// Magic bytes identifying an encrypted stock file. Chosen so it can never be
+/// mistaken for the first bytes of a plaintext strict-encoded `Stock`.
+const MAGIC: [u8; 8] = *b"RGBXSTK1";
+/// Header layout version. Bumped whenever the header shape below changes, so
+/// a future format change can still tell old files apart instead of
+/// misreading their bytes.
+const FORMAT_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// `m_cost`, `t_cost` and `p_cost`, each a little-endian `u32`.
+const PARAMS_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 1 + PARAMS_LEN + SALT_LEN + NONCE_LEN;
+
+/// Argon2id cost parameters used for every newly-written stock file. Fixed
+/// as explicit constants, rather than `Params::default()`, and persisted in
+/// the header on every write: if a future `argon2` upgrade changes its
+/// defaults, files written under this version remain decryptable because
+/// they carry the parameters they were actually derived with.
+const ARGON2_M_COST: u32 = 19_456;
+const ARGON2_T_COST: u32 = 2;
+const ARGON2_P_COST: u32 = 1;
+const ARGON2_OUTPUT_LEN: usize = 32;
+
+fn io_err(msg: impl Into<String>) -> io::Error { io::Error::new(ErrorKind::InvalidData, msg.into()) }
+
+fn derive_key(passphrase: &str, salt: &[u8; SALT_LEN], params: Params) -> Result<[u8; 32], WalletError> {
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| io_err(format!("unable to derive stock encryption key: {e}")))?;
+    Ok(key)
+}
+
+/// Reads and writes [`Stock`] as `header || ciphertext`, where `header` is
+/// `magic || version || argon2 params || salt || nonce` and `ciphertext` is
+/// the strict-encoded stock sealed with XChaCha20-Poly1305 under a key
+/// stretched from the caller's passphrase via Argon2id. The Argon2 params
+/// are written alongside the salt and nonce rather than assumed, so a stock
+/// written under one cost setting stays decryptable even after the defaults
+/// above change.
+pub struct EncryptedStock;
+
+impl EncryptedStock {
+    /// Returns `true` if `path` does not start with the encrypted-stock
+    /// magic, i.e. it is either absent or still a plaintext stock file.
+    pub fn is_plaintext(path: &Path) -> io::Result<bool> {
+        let mut file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(true),
+            Err(err) => return Err(err),
+        };
+        let mut magic = [0u8; MAGIC.len()];
+        match file.read_exact(&mut magic) {
+            Ok(()) => Ok(magic != MAGIC),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(true),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn load(path: &Path, passphrase: &str) -> Result<Stock, WalletError> {
+        let data = fs::read(path)?;
+        if data.len() < HEADER_LEN || data[..MAGIC.len()] != MAGIC {
+            return Err(io_err("stock file has no encrypted-stock header").into());
+        }
+
+        let mut offset = MAGIC.len();
+        let version = data[offset];
+        offset += 1;
+        if version != FORMAT_VERSION {
+            return Err(io_err(format!("unsupported encrypted-stock format version {version}")).into());
+        }
+
+        let m_cost = u32::from_le_bytes(data[offset..offset + 4].try_into().expect("4-byte slice"));
+        offset += 4;
+        let t_cost = u32::from_le_bytes(data[offset..offset + 4].try_into().expect("4-byte slice"));
+        offset += 4;
+        let p_cost = u32::from_le_bytes(data[offset..offset + 4].try_into().expect("4-byte slice"));
+        offset += 4;
+
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&data[offset..offset + SALT_LEN]);
+        offset += SALT_LEN;
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&data[offset..offset + NONCE_LEN]);
+        offset += NONCE_LEN;
+        let ciphertext = &data[offset..];
+
+        let params = Params::new(m_cost, t_cost, p_cost, Some(ARGON2_OUTPUT_LEN))
+            .map_err(|e| io_err(format!("stock file has invalid Argon2 parameters: {e}")))?;
+        let key = derive_key(passphrase, &salt, params)?;
+        let cipher = XChaCha20Poly1305::new(key.as_slice().into());
+        let plaintext = cipher
+            .decrypt(XNonce::from_slice(&nonce), ciphertext)
+            .map_err(|_| io_err("wrong stock passphrase or corrupted stock file"))?;
+        Stock::from_strict_serialized::<{ usize::MAX }>(plaintext.into()).map_err(WalletError::from)
+    }
+
+    pub fn store(stock: &Stock, path: &Path, passphrase: &str) -> Result<(), WalletError> {
+        let plaintext = stock
+            .to_strict_serialized::<{ usize::MAX }>()
+            .map_err(|e| io_err(format!("unable to serialize stock: {e}")))?;
+
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let params = Params::new(ARGON2_M_COST, ARGON2_T_COST, ARGON2_P_COST, Some(ARGON2_OUTPUT_LEN))
+            .map_err(|e| io_err(format!("invalid Argon2 parameters: {e}")))?;
+        let (m_cost, t_cost, p_cost) = (params.m_cost(), params.t_cost(), params.p_cost());
+        let key = derive_key(passphrase, &salt, params)?;
+        let cipher = XChaCha20Poly1305::new(key.as_slice().into());
+        let ciphertext = cipher
+            .encrypt(XNonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| io_err("unable to encrypt stock"))?;
+
+        let mut out = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+        out.extend_from_slice(&MAGIC);
+        out.push(FORMAT_VERSION);
+        out.extend_from_slice(&m_cost.to_le_bytes());
+        out.extend_from_slice(&t_cost.to_le_bytes());
+        out.extend_from_slice(&p_cost.to_le_bytes());
+        out.extend_from_slice(&salt);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+
+        // Write to a temp file first so a crash mid-write can't corrupt the
+        // previous, still-valid stock.
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, out)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}